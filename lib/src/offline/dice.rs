@@ -28,6 +28,13 @@ pub struct DiceOptions {
     /// QR code max version to use (max size)
     #[structopt(long, default_value = "14")]
     pub qr_version: i16,
+
+    /// Also emit the dice entropy encoded as human-transcribable words, as a backup to
+    /// write down alongside the key. This is NOT a BIP39 seed phrase: importing these
+    /// words into another wallet will derive a different key than this one. The backup
+    /// can only be restored by re-entering the same dice rolls into firma
+    #[structopt(long)]
+    backup_words: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -75,12 +82,56 @@ impl DiceOptions {
 pub fn roll(datadir: &str, network: Network, opt: &DiceOptions) -> Result<MasterKeyOutput> {
     opt.validate()?;
 
-    let master_key = calculate_key(&opt.launches, opt.faces as u32, network, &opt.key_name)?;
+    let mut master_key = calculate_key(&opt.launches, opt.faces as u32, network, &opt.key_name)?;
+    if opt.backup_words {
+        master_key.backup_words = Some(dice_backup_words(&opt.launches, opt.faces as u32));
+    }
     let output = save_keys(datadir, network, &opt.key_name, master_key, opt.qr_version)?;
 
     Ok(output)
 }
 
+/// Encode the exact entropy bytes handed to `PrivateMasterKey::new` (see `calculate_key`)
+/// as human-transcribable words, so the words carry exactly this key's seed bytes; the
+/// key itself is restored by re-entering the same dice rolls. The warning is baked into
+/// the returned string itself, since
+/// this is shown directly to the user in the key JSON/QR output: these are NOT a BIP39
+/// seed phrase (no checksum is appended, and firma seeds the xprv from raw entropy
+/// rather than the PBKDF2(mnemonic, passphrase) that standard BIP39 wallets use), so
+/// importing them into another wallet would silently produce a different key.
+fn dice_backup_words(launches: &[u32], faces: u32) -> String {
+    let acc = multiply_dice_launches(launches, faces);
+    let words = entropy_to_words(&acc.to_bytes_be());
+    format!(
+        "{} (NOT a BIP39 seed phrase -- importing into another wallet yields a different \
+         key; restore only by re-entering the same dice rolls into firma)",
+        words.join(" ")
+    )
+}
+
+/// Map raw entropy bytes onto words from the BIP39 English wordlist, purely reused here
+/// as a human-friendly dictionary: unlike BIP39 itself, no checksum is appended and no
+/// fixed entropy length is required, so the words encode exactly the given bytes and
+/// nothing else.
+fn entropy_to_words(entropy: &[u8]) -> Vec<&'static str> {
+    let wordlist = bip39::Language::English.word_list();
+    let mut bits: Vec<u8> = Vec::with_capacity(entropy.len() * 8);
+    for byte in entropy {
+        for i in (0..8).rev() {
+            bits.push((byte >> i) & 1);
+        }
+    }
+    while bits.len() % 11 != 0 {
+        bits.push(0);
+    }
+    bits.chunks(11)
+        .map(|chunk| {
+            let index = chunk.iter().fold(0usize, |acc, &bit| (acc << 1) | bit as usize);
+            wordlist[index]
+        })
+        .collect()
+}
+
 fn multiply_dice_launches(launches: &[u32], base: u32) -> BigUint {
     let init = BigUint::from(launches[0] - 1);
     launches.iter().skip(1).fold(init, |mut sum, i| {
@@ -188,6 +239,7 @@ mod tests {
             key_name: "a".to_string(),
             launches,
             qr_version: 14,
+            backup_words: false,
         };
 
         roll(&temp_dir_str, Network::Testnet, &opt).unwrap();
@@ -266,6 +318,24 @@ mod tests {
         assert_eq!(multiply_dice_launches(&vec![2], 2), BigUint::from(1u32));
     }
 
+    #[test]
+    fn test_entropy_to_words() {
+        let words = entropy_to_words(&[0u8; 16]);
+        assert_eq!(words.len(), 12);
+        assert_eq!(words[0], "abandon");
+
+        let words = entropy_to_words(&[0xffu8; 32]);
+        assert_eq!(words.len(), 24);
+    }
+
+    #[test]
+    fn test_dice_backup_words() {
+        // a single non-zero launch keeps the entropy byte string minimal (no leading
+        // zero byte), matching the same bytes `calculate_key` seeds the xprv with
+        let words = dice_backup_words(&vec![2], 2);
+        assert!(words.contains("NOT a BIP39 seed phrase"));
+    }
+
     #[test]
     fn test_master_from_dice() {
         // priv1.key and priv2.key taken from https://github.com/tyler-smith/go-bip32/blob/master/bip32_test.go
@@ -1,15 +1,61 @@
 use crate::list::ListOptions;
 use crate::*;
 use bitcoin::consensus::serialize;
-use bitcoin::util::bip32::{DerivationPath, Fingerprint};
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::util::bip32::{ChildNumber, DerivationPath, Fingerprint};
 use bitcoin::util::key;
 use bitcoin::{Address, Amount, Network, OutPoint, Script, SignedAmount, TxOut};
+use miniscript::{Descriptor, DescriptorPublicKey};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
 type HDKeypaths = BTreeMap<key::PublicKey, (Fingerprint, DerivationPath)>;
+type TapKeyOrigins =
+    BTreeMap<bitcoin::XOnlyPublicKey, (Vec<bitcoin::util::taproot::TapLeafHash>, (Fingerprint, DerivationPath))>;
+
+/// Denomination used to format the monetary fields of `PsbtPrettyPrint`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Denomination {
+    Btc,
+    MBtc,
+    Sat,
+}
+
+impl FromStr for Denomination {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "btc" => Ok(Denomination::Btc),
+            "mbtc" => Ok(Denomination::MBtc),
+            "sat" | "sats" | "satoshi" => Ok(Denomination::Sat),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} not in (btc, mbtc, sat)", s),
+            )),
+        }
+    }
+}
+
+fn format_amount(sat: u64, denomination: Denomination) -> String {
+    match denomination {
+        Denomination::Btc => Amount::from_sat(sat).to_string(),
+        Denomination::MBtc => format!("{:.5} mBTC", sat as f64 / 100_000f64),
+        Denomination::Sat => format!("{} sat", sat),
+    }
+}
+
+fn format_signed_amount(sat: i64, denomination: Denomination) -> String {
+    match denomination {
+        Denomination::Btc => SignedAmount::from_sat(sat).to_string(),
+        Denomination::MBtc => format!("{:.5} mBTC", sat as f64 / 100_000f64),
+        Denomination::Sat => format!("{} sat", sat),
+    }
+}
 
 /// Sign a Partially Signed Bitcoin Transaction (PSBT) with a key.
 #[derive(StructOpt, Debug, Serialize, Deserialize)]
@@ -17,15 +63,20 @@ type HDKeypaths = BTreeMap<key::PublicKey, (Fingerprint, DerivationPath)>;
 pub struct PrintOptions {
     /// PSBT json file
     psbt_file: PathBuf,
+
+    /// Denomination used to format the monetary values in the output (btc, mbtc, sat)
+    #[structopt(short, long, default_value = "btc")]
+    denomination: Denomination,
 }
 
 pub fn start(datadir: &str, network: Network, opt: &PrintOptions) -> Result<PsbtPrettyPrint> {
     let psbt = read_psbt(&opt.psbt_file)?;
+    let denomination = opt.denomination;
     let kind = Kind::Wallet;
     let opt = ListOptions { kind };
     let result = common::list::list(datadir, network, &opt)?;
     let wallets: Vec<WalletJson> = result.wallets.iter().map(|w| w.wallet.clone()).collect();
-    let output = pretty_print(&psbt, network, &wallets)?;
+    let output = pretty_print(&psbt, network, &wallets, denomination)?;
     Ok(output)
 }
 
@@ -33,6 +84,7 @@ pub fn pretty_print(
     psbt: &PSBT,
     network: Network,
     wallets: &[WalletJson],
+    denomination: Denomination,
 ) -> Result<PsbtPrettyPrint> {
     let mut result = PsbtPrettyPrint::default();
     let mut previous_outputs: Vec<TxOut> = vec![];
@@ -59,12 +111,19 @@ pub fn pretty_print(
 
     for (i, input) in tx.input.iter().enumerate() {
         let keypaths = &psbt.inputs[i].hd_keypaths;
-        let wallets = which_wallet(keypaths, &wallets);
+        let tap_key_origins = &psbt.inputs[i].tap_key_origins;
+        let wallets = which_wallet(
+            keypaths,
+            tap_key_origins,
+            &previous_outputs[i].script_pubkey,
+            &wallets,
+        );
         let txin = TxInOut {
             outpoint: Some(input.previous_output.to_string()),
             address: None,
-            value: Amount::from_sat(previous_outputs[i].value).to_string(),
-            path: derivation_paths(keypaths),
+            value: format_amount(previous_outputs[i].value, denomination),
+            value_sat: previous_outputs[i].value,
+            path: derivation_paths(keypaths, tap_key_origins),
             wallet: wallets.join(", "),
         };
         for wallet in wallets {
@@ -74,15 +133,30 @@ pub fn pretty_print(
     }
 
     for (i, output) in tx.output.iter().enumerate() {
-        let addr = Address::from_script(&output.script_pubkey, network)
-            .ok_or_else(fn_err("non default script"))?;
+        // `Address::from_script` returns None for some taproot outputs depending on the
+        // rust-bitcoin version in use; fall back to showing no address for those only
+        // (the wallet attribution below does not depend on it), since any other
+        // non-standard script should still surface as an error for the reviewer.
+        let addr = if let Some(addr) = Address::from_script(&output.script_pubkey, network) {
+            Some(addr.to_string())
+        } else if is_v1_p2tr(&output.script_pubkey) {
+            result.info.push(format!(
+                "Output #{} is a taproot (P2TR) output with no displayable address in this rust-bitcoin version",
+                i
+            ));
+            None
+        } else {
+            return Err(fn_err("non default script")());
+        };
         let keypaths = &psbt.outputs[i].hd_keypaths;
-        let wallets = which_wallet(keypaths, &wallets);
+        let tap_key_origins = &psbt.outputs[i].tap_key_origins;
+        let wallets = which_wallet(keypaths, tap_key_origins, &output.script_pubkey, &wallets);
         let txout = TxInOut {
             outpoint: None,
-            address: Some(addr.to_string()),
-            value: Amount::from_sat(output.value).to_string(),
-            path: derivation_paths(keypaths),
+            address: addr,
+            value: format_amount(output.value, denomination),
+            value_sat: output.value,
+            path: derivation_paths(keypaths, tap_key_origins),
             wallet: wallets.join(" ,"),
         };
         for wallet in wallets {
@@ -93,7 +167,7 @@ pub fn pretty_print(
     }
     let balances_vec: Vec<String> = balances
         .iter()
-        .map(|(k, v)| format!("{}: {}", k, SignedAmount::from_sat(*v).to_string()))
+        .map(|(k, v)| format!("{}: {}", k, format_signed_amount(*v, denomination)))
         .collect();
     result.balances = balances_vec.join("\n");
 
@@ -143,6 +217,25 @@ pub fn pretty_print(
         );
     }
 
+    // Replaceability and locktime: a signer reviewing the transaction offline should be
+    // able to tell whether it is replaceable or time-locked before signing.
+    let sequences: Vec<u32> = tx.input.iter().map(|i| i.sequence).collect();
+    let rbf = sequences.iter().any(|&s| s <= 0xFFFF_FFFD);
+    result.lock_time = tx.lock_time;
+    result.rbf = rbf;
+    result.sequences = sequences;
+    if rbf {
+        result.info.push(
+            "This transaction signals RBF (Replace-By-Fee): it can be replaced before confirmation".to_string(),
+        );
+    }
+    if tx.lock_time > 0 {
+        result.info.push(format!(
+            "This transaction has a locktime: it cannot be mined before {}",
+            tx.lock_time
+        ));
+    }
+
     let fee = input_values.iter().sum::<u64>() - output_values.iter().sum::<u64>();
     let tx_vbytes = tx.get_weight() / 4;
     let estimated_tx_vbytes = estimate_weight(psbt)? / 4;
@@ -155,13 +248,73 @@ pub fn pretty_print(
     };
     result.fee = Fee {
         absolute: fee,
-        absolute_fmt: Amount::from_sat(fee).to_string(),
+        absolute_fmt: format_amount(fee, denomination),
         rate: estimated_fee_rate,
     };
 
     Ok(result)
 }
 
+/// Estimate the final transaction weight once every input is satisfied, even though
+/// the PSBT's `unsigned_tx` carries no scriptSigs/witnesses yet. `tx.get_weight()`
+/// already accounts for the outputs and the (empty) input scriptSigs/witnesses, so we
+/// add, per input, the spending cost implied by its previous script type.
+fn estimate_weight(psbt: &PSBT) -> Result<usize> {
+    let tx = &psbt.global.unsigned_tx;
+    let mut weight = tx.get_weight();
+
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let previous_script = match (&input.non_witness_utxo, &input.witness_utxo) {
+            (Some(prev_tx), None) => {
+                let outpoint = tx.input[i].previous_output;
+                prev_tx
+                    .output
+                    .get(outpoint.vout as usize)
+                    .ok_or_else(fn_err("can't find txout"))?
+                    .script_pubkey
+                    .clone()
+            }
+            (None, Some(val)) => val.script_pubkey.clone(),
+            _ => return Err("witness_utxo and non_witness_utxo are both None or both Some".into()),
+        };
+
+        weight += if is_v1_p2tr(&previous_script) {
+            estimate_taproot_input_weight(input)
+        } else if previous_script.is_v0_p2wpkh() {
+            // witness: item count + <len><sig ~72> + <len><pubkey 33>, 1 weight unit/byte
+            1 + 1 + 72 + 1 + 33
+        } else if previous_script.is_v0_p2wsh() {
+            // redeem script size isn't known upfront; keep the same conservative byte
+            // budget as p2wpkh, but at the witness weight rate (1 WU/byte) rather than
+            // the legacy scriptSig rate below, which would overestimate it ~4x
+            1 + 1 + 72 + 1 + 33
+        } else {
+            // legacy p2pkh/p2pk/p2sh: sig + pubkey in the scriptSig, full weight
+            4 * (1 + 72 + 1 + 33)
+        };
+    }
+
+    Ok(weight)
+}
+
+/// A taproot key-path spend's witness is a single Schnorr signature (64 bytes, or 65
+/// when the input records any sighash type, an explicit default one included), plus the
+/// item-count and length-prefix bytes (~66 bytes total). A script-path spend additionally
+/// carries the control block and the revealed leaf script of the first `tap_scripts`
+/// entry; exactly one signature is budgeted to satisfy that leaf script.
+fn estimate_taproot_input_weight(input: &bitcoin::util::psbt::Input) -> usize {
+    if let Some((control_block, (script, _leaf_version))) = input.tap_scripts.iter().next() {
+        let sig_len = if input.sighash_type.is_some() { 65 } else { 64 };
+        1 // witness item count
+            + 1 + control_block.serialize().len() // control block
+            + 1 + script.len() // revealed leaf script
+            + 1 + sig_len // one signature satisfying the leaf script
+    } else {
+        let sig_len = if input.sighash_type.is_some() { 65 } else { 64 };
+        1 + 1 + sig_len
+    }
+}
+
 fn biggest_dividing_pow(num: u64) -> u8 {
     let mut start = 10u64;
     let mut count = 0u8;
@@ -174,42 +327,91 @@ fn biggest_dividing_pow(num: u64) -> u8 {
     }
 }
 
-const SCRIPT_TYPE_FN: [fn(&Script) -> bool; 5] = [
+const SCRIPT_TYPE_FN: [fn(&Script) -> bool; 6] = [
     Script::is_p2pk,
     Script::is_p2pkh,
     Script::is_p2sh,
     Script::is_v0_p2wpkh,
     Script::is_v0_p2wsh,
+    is_v1_p2tr,
 ];
 fn script_type(script: &Script) -> Option<usize> {
     SCRIPT_TYPE_FN.iter().position(|f| f(script))
 }
 
-pub fn derivation_paths(hd_keypaths: &HDKeypaths) -> String {
+/// A BIP341 taproot output: a v1 witness program carrying a single 32-byte push
+/// (the output key). `Script` has no `is_v1_p2tr` of its own, so check by hand.
+fn is_v1_p2tr(script: &Script) -> bool {
+    let bytes = script.as_bytes();
+    script.is_witness_program()
+        && bytes.first() == Some(&bitcoin::blockdata::opcodes::all::OP_PUSHNUM_1.into_u8())
+        && bytes.len() == 34
+}
+
+pub fn derivation_paths(hd_keypaths: &HDKeypaths, tap_key_origins: &TapKeyOrigins) -> String {
     let mut vec: Vec<String> = hd_keypaths
         .iter()
         .map(|(_, (_, p))| format!("{:?}", p))
+        .chain(
+            tap_key_origins
+                .iter()
+                .map(|(_, (_, (_, p)))| format!("{:?}", p)),
+        )
         .collect();
     vec.sort();
     vec.dedup();
     vec.join(", ")
 }
 
-fn which_wallet(hd_keypaths: &HDKeypaths, wallets: &[WalletJson]) -> Vec<String> {
-    // TODO this should be done with miniscript
+/// Attribute an input/output to a wallet by deriving the wallet's receive and change
+/// descriptors at the BIP32 path found in its keypaths and comparing against the
+/// actual script_pubkey, rather than just checking fingerprint overlap (which misfires
+/// on multisig wallets where only some cosigners' fingerprints are present).
+fn which_wallet(
+    hd_keypaths: &HDKeypaths,
+    tap_key_origins: &TapKeyOrigins,
+    script_pubkey: &Script,
+    wallets: &[WalletJson],
+) -> Vec<String> {
+    let secp = Secp256k1::verification_only();
+    let paths: Vec<&DerivationPath> = hd_keypaths
+        .values()
+        .map(|(_, p)| p)
+        .chain(tap_key_origins.values().map(|(_, (_, p))| p))
+        .collect();
+
     let mut result = vec![];
     for wallet in wallets {
-        if !hd_keypaths.is_empty()
-            && hd_keypaths
+        let descriptors: Vec<Descriptor<DescriptorPublicKey>> =
+            [&wallet.descriptor_main, &wallet.descriptor_change]
                 .iter()
-                .all(|(_, (f, _))| wallet.fingerprints.contains(f))
-        {
+                .filter_map(|d| Descriptor::<DescriptorPublicKey>::from_str(d).ok())
+                .collect();
+
+        let is_match = descriptors.iter().any(|descriptor| {
+            paths.iter().any(|path| {
+                last_unhardened_index(path)
+                    .and_then(|index| descriptor.derived_descriptor(&secp, index).ok())
+                    .map(|derived| derived.script_pubkey() == *script_pubkey)
+                    .unwrap_or(false)
+            })
+        });
+        if is_match {
             result.push(wallet.name.to_string())
         }
     }
     result
 }
 
+/// Wallet descriptors carry a single wildcard (`*`) child; the address index used to
+/// derive the concrete script is the last, non-hardened component of the keypath.
+fn last_unhardened_index(path: &DerivationPath) -> Option<u32> {
+    match path.into_iter().last()? {
+        ChildNumber::Normal { index } => Some(*index),
+        ChildNumber::Hardened { .. } => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::offline::print::{biggest_dividing_pow, script_type};
@@ -243,5 +445,8 @@ mod tests {
 
         let s = hex_script!("00201775ead41acefa14d2d534d6272da610cc35855d0de4cab0f5c1a3f894921989");
         assert_eq!(script_type(&s), Some(4usize));
+
+        let s = hex_script!("5120a60f26097a6c58a4bb6f75a5fb6e9aa68b8a8e4fa7e36faaacf4753f8efd0d2e");
+        assert_eq!(script_type(&s), Some(5usize));
     }
 }